@@ -0,0 +1,33 @@
+// Requires nightly for the unstable `test` harness; run with
+// `cargo +nightly bench --features simd`.
+#![feature(test)]
+
+extern crate aabb_quadtree;
+extern crate test;
+
+use aabb_quadtree::geom::{Point, Rect};
+use test::Bencher;
+
+fn candidates(n: usize) -> Vec<Rect<f32>> {
+    (0..n)
+        .map(|i| {
+            let x = i as f32;
+            Rect::from_points(&Point { x: x, y: x }, &Point { x: x + 1.0, y: x + 1.0 })
+        })
+        .collect()
+}
+
+#[bench]
+fn scalar_intersects_any(b: &mut Bencher) {
+    let query = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 2.0, y: 2.0 });
+    let candidates = candidates(10_000);
+    b.iter(|| Rect::intersects_any(&query, &candidates));
+}
+
+#[cfg(feature = "simd")]
+#[bench]
+fn simd_intersects_any(b: &mut Bencher) {
+    let query = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 2.0, y: 2.0 });
+    let candidates = candidates(10_000);
+    b.iter(|| aabb_quadtree::geom::simd::intersects_any_f32(&query, &candidates));
+}
@@ -1,34 +1,136 @@
 #![allow(dead_code, missing_docs)]
 
 
-use std::ops::{Add, Neg, Sub};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A numeric type usable as a coordinate in `Point`, `Vector`, and `Rect`.
+///
+/// Implemented for `f32`, `f64`, and the built-in integer types, so the
+/// geometry in this module can back either float-space or integer/tile-grid
+/// quadtrees. `rmin`/`rmax` exist (rather than relying on `PartialOrd`
+/// directly) so that float impls can special-case `NaN` the same way the
+/// old hand-rolled `min`/`max` helpers did.
+pub trait Scalar: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> {
+    /// The additive identity.
+    fn zero() -> Self;
+    /// The multiplicative identity.
+    fn one() -> Self;
+    /// Two, as a value of `Self`. Used to halve extents when splitting rects.
+    fn two() -> Self;
+    /// Like `min`, but on float scalars a `NaN` operand loses to the other value.
+    fn rmin(self, other: Self) -> Self;
+    /// Like `max`, but on float scalars a `NaN` operand loses to the other value.
+    fn rmax(self, other: Self) -> Self;
+    /// A sentinel value usable to mark "no value yet", if one exists for
+    /// this type. `Some(NaN)` for float scalars; `None` for integer
+    /// scalars, which have no spare value to dedicate to the role. Backs
+    /// `Rect::try_null`.
+    fn null_sentinel() -> Option<Self> { None }
+}
+
+/// A `Scalar` that also supports the handful of operations (`NaN`,
+/// infinity, square roots) that only make sense for floating point
+/// coordinates. `Rect::null`, distance, and magnitude all live behind this
+/// bound rather than `Scalar` directly.
+pub trait FloatScalar: Scalar {
+    /// A `NaN` value, used as the historical "uninitialized rect" sentinel.
+    fn nan() -> Self;
+    /// Positive infinity.
+    fn infinity() -> Self;
+    /// Whether this value is `NaN`.
+    fn is_nan(self) -> bool;
+    /// The square root of this value.
+    fn sqrt(self) -> Self;
+    /// The sine of this value, in radians.
+    fn sin(self) -> Self;
+    /// The cosine of this value, in radians.
+    fn cos(self) -> Self;
+}
+
+macro_rules! impl_scalar_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Scalar for $t {
+                fn zero() -> Self { 0 as $t }
+                fn one() -> Self { 1 as $t }
+                fn two() -> Self { 2 as $t }
+                fn rmin(self, other: Self) -> Self { if self < other { self } else { other } }
+                fn rmax(self, other: Self) -> Self { if self > other { self } else { other } }
+            }
+        )*
+    };
+}
 
+impl_scalar_for_int!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! impl_scalar_for_float {
+    ($($t:ty),*) => {
+        $(
+            impl Scalar for $t {
+                fn zero() -> Self { 0.0 }
+                fn one() -> Self { 1.0 }
+                fn two() -> Self { 2.0 }
+                fn rmin(self, other: Self) -> Self {
+                    if self.is_nan() { other } else if other.is_nan() { self } else if self < other { self } else { other }
+                }
+                fn rmax(self, other: Self) -> Self {
+                    if self.is_nan() { other } else if other.is_nan() { self } else if self > other { self } else { other }
+                }
+                fn null_sentinel() -> Option<Self> { Some(<$t>::NAN) }
+            }
+
+            impl FloatScalar for $t {
+                fn nan() -> Self { <$t>::NAN }
+                fn infinity() -> Self { <$t>::INFINITY }
+                fn is_nan(self) -> bool { <$t>::is_nan(self) }
+                fn sqrt(self) -> Self { <$t>::sqrt(self) }
+                fn sin(self) -> Self { <$t>::sin(self) }
+                fn cos(self) -> Self { <$t>::cos(self) }
+            }
+        )*
+    };
+}
+
+impl_scalar_for_float!(f32, f64);
+
+/// A point in 2d space.
+///
+/// Defaults to `f32` coordinates so existing callers can keep writing bare
+/// `Point` (and `geom::Point<i32>` etc. for integer/tile-grid space).
 #[derive(PartialOrd, PartialEq, Copy, Clone, Debug)]
-pub struct Point {
-    pub x: f32,
-    pub y: f32,
+pub struct Point<T = f32> {
+    pub x: T,
+    pub y: T,
 }
 
+/// A 2d displacement.
 #[derive(PartialOrd, PartialEq, Copy, Clone, Debug)]
-pub struct Vector {
-    pub x: f32,
-    pub y: f32,
+pub struct Vector<T = f32> {
+    pub x: T,
+    pub y: T,
 }
 
+/// An axis-aligned bounding box.
 #[derive(PartialOrd, PartialEq, Copy, Clone, Debug)]
-pub struct Rect {
-    pub top_left: Point,
-    pub bottom_right: Point,
+pub struct Rect<T = f32> {
+    pub top_left: Point<T>,
+    pub bottom_right: Point<T>,
 }
 
-impl Neg for Vector {
-    type Output = Vector;
-    fn neg(self) -> Vector { Vector { x: -self.x, y: -self.y } }
+impl<T> Neg for Vector<T>
+where
+    T: Neg<Output = T>,
+{
+    type Output = Vector<T>;
+    fn neg(self) -> Vector<T> { Vector { x: -self.x, y: -self.y } }
 }
 
-impl Sub<Vector> for Point {
-    type Output = Point;
-    fn sub(self, rhs: Vector) -> Point {
+impl<T> Sub<Vector<T>> for Point<T>
+where
+    T: Sub<Output = T>,
+{
+    type Output = Point<T>;
+    fn sub(self, rhs: Vector<T>) -> Point<T> {
         Point {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -36,9 +138,12 @@ impl Sub<Vector> for Point {
     }
 }
 
-impl Add<Vector> for Point {
-    type Output = Point;
-    fn add(self, rhs: Vector) -> Point {
+impl<T> Add<Vector<T>> for Point<T>
+where
+    T: Add<Output = T>,
+{
+    type Output = Point<T>;
+    fn add(self, rhs: Vector<T>) -> Point<T> {
         Point {
             x: self.x + rhs.x,
             y: self.y + rhs.y,
@@ -46,9 +151,12 @@ impl Add<Vector> for Point {
     }
 }
 
-impl Sub<Point> for Vector {
-    type Output = Point;
-    fn sub(self, rhs: Point) -> Point {
+impl<T> Sub<Point<T>> for Vector<T>
+where
+    T: Sub<Output = T>,
+{
+    type Output = Point<T>;
+    fn sub(self, rhs: Point<T>) -> Point<T> {
         Point {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -56,9 +164,12 @@ impl Sub<Point> for Vector {
     }
 }
 
-impl Add<Point> for Vector {
-    type Output = Point;
-    fn add(self, rhs: Point) -> Point {
+impl<T> Add<Point<T>> for Vector<T>
+where
+    T: Add<Output = T>,
+{
+    type Output = Point<T>;
+    fn add(self, rhs: Point<T>) -> Point<T> {
         Point {
             x: self.x + rhs.x,
             y: self.y + rhs.y,
@@ -67,9 +178,12 @@ impl Add<Point> for Vector {
 }
 
 
-impl Sub<Point> for Point {
-    type Output = Vector;
-    fn sub(self, rhs: Point) -> Vector {
+impl<T> Sub<Point<T>> for Point<T>
+where
+    T: Sub<Output = T>,
+{
+    type Output = Vector<T>;
+    fn sub(self, rhs: Point<T>) -> Vector<T> {
         Vector {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -77,43 +191,37 @@ impl Sub<Point> for Point {
     }
 }
 
-impl Rect {
-    pub fn centered_with_radius(p1: &Point, radius: f32) -> Rect {
+impl<T: Scalar> Rect<T> {
+    pub fn centered_with_radius(p1: &Point<T>, radius: T) -> Rect<T> {
         let v = Vector { x: radius, y: radius };
         Rect::from_points(&(*p1 - v), &(*p1 + v))
     }
 
-    pub fn from_points(p1: &Point, p2: &Point) -> Rect {
-        let mut r = Rect::null_at(&p1);
-        r.expand_to_include(&p2);
-        r
-    }
-
-    pub fn from_point_and_size(point: &Point, size: &Vector) -> Rect {
-        assert!(size.x > 0.0);
-        assert!(size.y > 0.0);
-        Rect {
-            top_left: *point,
-            bottom_right: *point + *size,
-        }
-    }
-
-    pub fn null() -> Rect {
-        let nan = ::std::f32::NAN;
+    /// Builds the tight bounding rect spanning `p1` and `p2`, regardless of
+    /// which corner each point represents.
+    pub fn from_points(p1: &Point<T>, p2: &Point<T>) -> Rect<T> {
         Rect {
-            top_left: Point { x: nan, y: nan },
-            bottom_right: Point { x: nan, y: nan },
+            top_left: Point {
+                x: p1.x.rmin(p2.x),
+                y: p1.y.rmin(p2.y),
+            },
+            bottom_right: Point {
+                x: p1.x.rmax(p2.x),
+                y: p1.y.rmax(p2.y),
+            },
         }
     }
 
-    pub fn null_at(point: &Point) -> Rect {
+    pub fn from_point_and_size(point: &Point<T>, size: &Vector<T>) -> Rect<T> {
+        assert!(size.x > T::zero());
+        assert!(size.y > T::zero());
         Rect {
             top_left: *point,
-            bottom_right: *point,
+            bottom_right: *point + *size,
         }
     }
 
-    pub fn expand(&self, left: f32, top: f32, right: f32, bottom: f32) -> Rect {
+    pub fn expand(&self, left: T, top: T, right: T, bottom: T) -> Rect<T> {
         let top_left_vec = Vector { x: left, y: top };
         let bottom_right_vec = Vector { x: right, y: bottom };
         Rect {
@@ -122,109 +230,91 @@ impl Rect {
         }
     }
 
-    pub fn width(&self) -> f32 { self.bottom_right.x - self.top_left.x }
+    pub fn width(&self) -> T { self.bottom_right.x - self.top_left.x }
 
-    pub fn height(&self) -> f32 { self.bottom_right.y - self.top_left.y }
+    pub fn height(&self) -> T { self.bottom_right.y - self.top_left.y }
 
-    pub fn left(&self) -> f32 { self.top_left.x }
+    pub fn left(&self) -> T { self.top_left.x }
 
-    pub fn right(&self) -> f32 { self.bottom_right.x }
+    pub fn right(&self) -> T { self.bottom_right.x }
 
-    pub fn top(&self) -> f32 { self.top_left.y }
+    pub fn top(&self) -> T { self.top_left.y }
 
-    pub fn bottom(&self) -> f32 { self.bottom_right.y }
+    pub fn bottom(&self) -> T { self.bottom_right.y }
 
-    pub fn top_left(&self) -> Point { self.top_left }
+    pub fn top_left(&self) -> Point<T> { self.top_left }
 
-    pub fn bottom_right(&self) -> Point { self.bottom_right }
+    pub fn bottom_right(&self) -> Point<T> { self.bottom_right }
 
-    pub fn bottom_left(&self) -> Point {
+    pub fn bottom_left(&self) -> Point<T> {
         Point {
             x: self.top_left().x,
             y: self.bottom_right().y,
         }
     }
 
-    pub fn top_right(&self) -> Point {
+    pub fn top_right(&self) -> Point<T> {
         Point {
             x: self.bottom_right().x,
             y: self.top_left().y,
         }
     }
 
-    pub fn north(&self) -> Point {
+    pub fn north(&self) -> Point<T> {
         Point {
-            x: self.left() + self.width() / 2.0,
+            x: self.left() + self.width() / T::two(),
             y: self.top(),
         }
     }
 
-    pub fn south(&self) -> Point {
+    pub fn south(&self) -> Point<T> {
         Point {
-            x: self.left() + self.width() / 2.0,
+            x: self.left() + self.width() / T::two(),
             y: self.bottom(),
         }
     }
 
-    pub fn west(&self) -> Point {
+    pub fn west(&self) -> Point<T> {
         Point {
             x: self.left(),
-            y: self.top() + self.height() / 2.0,
+            y: self.top() + self.height() / T::two(),
         }
     }
 
-    pub fn east(&self) -> Point {
+    pub fn east(&self) -> Point<T> {
         Point {
             x: self.right(),
-            y: self.top() + self.height() / 2.0,
+            y: self.top() + self.height() / T::two(),
         }
     }
 
 
-    pub fn expanded_by(&self, point: &Point) -> Rect {
+    pub fn expanded_by(&self, point: &Point<T>) -> Rect<T> {
         let mut r = self.clone();
         r.expand_to_include(point);
         r
     }
 
-    pub fn is_null(&self) -> bool {
-        self.top_left.x.is_nan() || self.top_left.y.is_nan() || self.bottom_right.x.is_nan() || self.bottom_right.y.is_nan()
-    }
-
-    pub fn expand_to_include(&mut self, point: &Point) {
-        fn min(a: f32, b: f32) -> f32 {
-            if a.is_nan() { return b; }
-            if b.is_nan() { return a; }
-            if a < b { return a; }
-            return b;
-        }
-
-        fn max(a: f32, b: f32) -> f32 {
-            if a.is_nan() { return b; }
-            if b.is_nan() { return a; }
-            if a > b { return a; }
-            return b;
-        }
+    pub fn expand_to_include(&mut self, point: &Point<T>) {
+        self.top_left.x = self.top_left.x.rmin(point.x);
+        self.top_left.y = self.top_left.y.rmin(point.y);
 
-        self.top_left.x = min(self.top_left.x, point.x);
-        self.top_left.y = min(self.top_left.y, point.y);
-
-        self.bottom_right.x = max(self.bottom_right.x, point.x);
-        self.bottom_right.y = max(self.bottom_right.y, point.y);
+        self.bottom_right.x = self.bottom_right.x.rmax(point.x);
+        self.bottom_right.y = self.bottom_right.y.rmax(point.y);
     }
 
-    pub fn union_with(&self, other: &Rect) -> Rect {
+    pub fn union_with(&self, other: &Rect<T>) -> Rect<T> {
         let mut r = self.clone();
         r.expand_to_include(&other.top_left);
         r.expand_to_include(&other.bottom_right);
         r
     }
 
-    pub fn contains(&self, p: &Point) -> bool {
+    pub fn contains(&self, p: &Point<T>) -> bool {
         p.x >= self.top_left.x && p.x < self.bottom_right.x && p.y >= self.top_left.y && p.y < self.bottom_right.y
     }
 
-    pub fn does_intersect(&self, other: &Rect) -> bool {
+    pub fn does_intersect(&self, other: &Rect<T>) -> bool {
         let r1 = self;
         let r2 = other;
 
@@ -233,55 +323,48 @@ impl Rect {
         !(r2.left() > r1.right() || r2.right() < r1.left() || r2.top() > r1.bottom() || r2.bottom() < r1.top())
     }
 
-    pub fn intersect_with(&self, other: &Rect) -> Rect {
-        if !self.does_intersect(other) {
-            return Rect::null();
-        }
-        let left = self.left().max(other.left());
-        let right = self.right().min(other.right());
-
-        let top = self.top().max(other.top());
-        let bottom = self.bottom().min(other.bottom());
-
-        Rect::from_points(&Point { x: left, y: top }, &Point { x: right, y: bottom })
-    }
-
-    pub fn midpoint(&self) -> Point {
+    pub fn midpoint(&self) -> Point<T> {
         let half = Vector {
-            x: self.width() / 2.0,
-            y: self.height() / 2.0,
+            x: self.width() / T::two(),
+            y: self.height() / T::two(),
         };
         self.top_left() + half
     }
 
-    pub fn split_vert(&self) -> (Rect, Rect) {
+    pub fn split_vert(&self) -> (Rect<T>, Rect<T>) {
         let half_size = Vector {
-            x: self.width() / 2.0,
+            x: self.width() / T::two(),
             y: self.height(),
         };
-        let half_offset = Vector { x: self.width() / 2.0, y: 0.0 };
+        let half_offset = Vector {
+            x: self.width() / T::two(),
+            y: T::zero(),
+        };
         (
             Rect::from_point_and_size(&self.top_left, &half_size),
             Rect::from_point_and_size(&(self.top_left + half_offset), &half_size),
         )
     }
 
-    pub fn split_hori(&self) -> (Rect, Rect) {
+    pub fn split_hori(&self) -> (Rect<T>, Rect<T>) {
         let half_size = Vector {
             x: self.width(),
-            y: self.height() / 2.0,
+            y: self.height() / T::two(),
+        };
+        let half_offset = Vector {
+            x: T::zero(),
+            y: self.height() / T::two(),
         };
-        let half_offset = Vector { x: 0.0, y: self.height() / 2.0 };
         (
             Rect::from_point_and_size(&self.top_left, &half_size),
             Rect::from_point_and_size(&(self.top_left + half_offset), &half_size),
         )
     }
 
-    pub fn split_quad(&self) -> [Rect; 4] {
+    pub fn split_quad(&self) -> [Rect<T>; 4] {
         let half = Vector {
-            x: self.width() / 2.0,
-            y: self.height() / 2.0,
+            x: self.width() / T::two(),
+            y: self.height() / T::two(),
         };
         [
             // x _
@@ -311,41 +394,441 @@ impl Rect {
         ]
     }
 
-    pub fn close_to(&self, other: &Rect, epsilon: f32) -> bool {
+    pub fn close_to(&self, other: &Rect<T>, epsilon: T) -> bool {
         self.top_left.close_to(&other.top_left, epsilon) && self.bottom_right.close_to(&other.bottom_right, epsilon)
     }
+
+    /// Returns true if `query` intersects any rect in `candidates`.
+    ///
+    /// This is the scalar path, used for every scalar type including
+    /// `f32`. It never dispatches to the vectorized
+    /// `simd::intersects_any_f32`, which `f32` callers can call directly
+    /// when the `simd` feature is on (see that module's docs).
+    pub fn intersects_any(query: &Rect<T>, candidates: &[Rect<T>]) -> bool {
+        candidates.iter().any(|c| query.does_intersect(c))
+    }
+
+    /// Returns the indices of every rect in `candidates` that intersects `query`.
+    pub fn filter_intersecting(query: &Rect<T>, candidates: &[Rect<T>]) -> Vec<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|&(_, c)| query.does_intersect(c))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// A degenerate rect containing only `point`. Unlike `null`, this needs
+    /// no sentinel value and so works for integer scalars too; it's the
+    /// usual seed for accumulating a bounding rect via `expand_to_include`.
+    pub fn null_at(point: &Point<T>) -> Rect<T> {
+        Rect {
+            top_left: *point,
+            bottom_right: *point,
+        }
+    }
+
+    /// An `Option`-returning counterpart to `null` that's available for
+    /// every scalar type, not just floats: `Some` with `null`'s NaN-cornered
+    /// rect for float scalars, `None` for integer scalars, which have no
+    /// sentinel value to spare. Prefer this over `null` when `T` isn't
+    /// known to be a `FloatScalar`; fall back to `null_at` if you already
+    /// have a real point to seed the rect from.
+    pub fn try_null() -> Option<Rect<T>> {
+        let s = T::null_sentinel()?;
+        Some(Rect {
+            top_left: Point { x: s, y: s },
+            bottom_right: Point { x: s, y: s },
+        })
+    }
 }
 
-impl Vector {
-    pub fn magnitude(&self) -> f32 { (self.x * self.x + self.y * self.y).sqrt() }
+impl<T: FloatScalar> Rect<T> {
+    /// A rect with no points in it yet, represented with `NaN` corners.
+    /// Only available for float scalars, since integer types have no `NaN`
+    /// to use as a sentinel; build up integer rects via `from_points` or
+    /// `null_at` starting from a real point instead.
+    pub fn null() -> Rect<T> {
+        let nan = T::nan();
+        Rect {
+            top_left: Point { x: nan, y: nan },
+            bottom_right: Point { x: nan, y: nan },
+        }
+    }
 
-    pub fn normalized(&self) -> Vector {
-        let m = self.magnitude();
-        Vector { x: self.x / m, y: self.y / m }
+    pub fn is_null(&self) -> bool {
+        self.top_left.x.is_nan() || self.top_left.y.is_nan() || self.bottom_right.x.is_nan() || self.bottom_right.y.is_nan()
+    }
+
+    pub fn intersect_with(&self, other: &Rect<T>) -> Rect<T> {
+        if !self.does_intersect(other) {
+            return Rect::null();
+        }
+        let left = self.left().rmax(other.left());
+        let right = self.right().rmin(other.right());
+
+        let top = self.top().rmax(other.top());
+        let bottom = self.bottom().rmin(other.bottom());
+
+        Rect::from_points(&Point { x: left, y: top }, &Point { x: right, y: bottom })
     }
 
-    pub fn mul_e(&self, other: &Vector) -> Vector {
+    /// The squared distance from `p` to the nearest point on this rect
+    /// (zero if `p` is inside). It skips the square root that
+    /// `distance_to_point` takes, which is enough when only the ordering
+    /// of distances matters, as in a nearest-neighbor branch-and-bound
+    /// prune.
+    ///
+    /// Only defined for float scalars: the per-axis clamp distance
+    /// (`p.x - self.right()`, `self.left() - p.x`) goes negative whenever
+    /// `p` is inside that axis's span, which underflows for unsigned
+    /// integer scalars instead of producing the negative value `rmax`
+    /// needs to clamp away.
+    pub fn distance_to_point_2(&self, p: &Point<T>) -> T {
+        let dx = (p.x - self.right()).rmax(self.left() - p.x).rmax(T::zero());
+        let dy = (p.y - self.bottom()).rmax(self.top() - p.y).rmax(T::zero());
+        dx * dx + dy * dy
+    }
+
+    /// The distance from `p` to the nearest point on this rect (zero if `p`
+    /// is inside). This is the metric a nearest-neighbor traversal over the
+    /// quadtree needs to prune branches whose bounding rect can't possibly
+    /// beat the current best match.
+    pub fn distance_to_point(&self, p: &Point<T>) -> T { self.distance_to_point_2(p).sqrt() }
+
+    /// Returns the parameter `t` such that `origin + t * dir` is where the
+    /// ray first enters this rect, or `None` if the ray never does. `t` is
+    /// in units of `dir`, not a true distance, unless `dir` is normalized
+    /// (see `Vector::normalized`) — e.g. `t == 0.5` means "halfway along
+    /// `dir`", not "0.5 units away".
+    ///
+    /// Uses the slab method: each axis narrows the `[tmin, tmax]` interval
+    /// for which the ray is inside that axis's slab, and the rect is hit
+    /// iff the two axes' intervals still overlap at the end.
+    pub fn ray_intersection(&self, origin: &Point<T>, dir: &Vector<T>) -> Option<T> {
+        let mut tmin = T::zero();
+        let mut tmax = T::infinity();
+
+        if dir.x == T::zero() {
+            if origin.x < self.left() || origin.x > self.right() {
+                return None;
+            }
+        } else {
+            let inv_dir = T::one() / dir.x;
+            let t1 = (self.left() - origin.x) * inv_dir;
+            let t2 = (self.right() - origin.x) * inv_dir;
+            tmin = tmin.rmax(t1.rmin(t2));
+            tmax = tmax.rmin(t1.rmax(t2));
+        }
+
+        if dir.y == T::zero() {
+            if origin.y < self.top() || origin.y > self.bottom() {
+                return None;
+            }
+        } else {
+            let inv_dir = T::one() / dir.y;
+            let t1 = (self.top() - origin.y) * inv_dir;
+            let t2 = (self.bottom() - origin.y) * inv_dir;
+            tmin = tmin.rmax(t1.rmin(t2));
+            tmax = tmax.rmin(t1.rmax(t2));
+        }
+
+        if tmax >= tmin && tmax >= T::zero() { Some(tmin) } else { None }
+    }
+
+    /// Like `ray_intersection`, but treats `dir` as a finite segment from
+    /// `origin` to `origin + *dir` rather than an infinite ray: a hit whose
+    /// `t` would land past the end of the segment is not reported.
+    pub fn segment_intersection(&self, origin: &Point<T>, dir: &Vector<T>) -> Option<T> {
+        self.ray_intersection(origin, dir).filter(|&t| t <= T::one())
+    }
+}
+
+impl<T: Scalar> Rect<T> {
+    /// Transforms all four corners by `m` and returns the tight AABB that
+    /// encloses them. A rotation doesn't stay axis-aligned, so this is the
+    /// way to keep a moved/rotated object's bounds usable by the quadtree.
+    pub fn transformed_bounds(&self, m: &Affine2<T>) -> Rect<T> {
+        let mut bounds = Rect::null_at(&m.apply(&self.top_left()));
+        bounds.expand_to_include(&m.apply(&self.top_right()));
+        bounds.expand_to_include(&m.apply(&self.bottom_left()));
+        bounds.expand_to_include(&m.apply(&self.bottom_right()));
+        bounds
+    }
+}
+
+/// A 2D affine transform: a linear part (a row-major 2x2 matrix) applied
+/// before a translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Affine2<T = f32> {
+    pub matrix: [T; 4],
+    pub translation: Vector<T>,
+}
+
+impl<T: Scalar> Affine2<T> {
+    pub fn identity() -> Affine2<T> {
+        Affine2 {
+            matrix: [T::one(), T::zero(), T::zero(), T::one()],
+            translation: Vector { x: T::zero(), y: T::zero() },
+        }
+    }
+
+    pub fn translation(v: Vector<T>) -> Affine2<T> {
+        Affine2::identity().with_translation(v)
+    }
+
+    pub fn with_translation(mut self, v: Vector<T>) -> Affine2<T> {
+        self.translation = v;
+        self
+    }
+
+    pub fn apply(&self, p: &Point<T>) -> Point<T> { p.transform(&self.matrix) + self.translation }
+}
+
+impl<T: FloatScalar + Neg<Output = T>> Affine2<T> {
+    pub fn rotation(angle: T) -> Affine2<T> {
+        let (s, c) = (angle.sin(), angle.cos());
+        Affine2 {
+            matrix: [c, -s, s, c],
+            translation: Vector { x: T::zero(), y: T::zero() },
+        }
+    }
+}
+
+impl<T: Scalar> Vector<T> {
+    pub fn mul_e(&self, other: &Vector<T>) -> Vector<T> {
         Vector {
             x: self.x * other.x,
             y: self.y * other.y,
         }
     }
 
-    pub fn scale_e(&self, sx: f32, sy: f32) -> Vector { Vector { x: self.x * sx, y: self.y * sy } }
+    pub fn scale_e(&self, sx: T, sy: T) -> Vector<T> { Vector { x: self.x * sx, y: self.y * sy } }
 
-    pub fn cross(&self, other: &Vector) -> f32 { self.x * other.y - self.y * other.x }
+    pub fn cross(&self, other: &Vector<T>) -> T { self.x * other.y - self.y * other.x }
 
-    pub fn dot(&self, other: &Vector) -> f32 { self.x * other.x + self.y * other.y }
+    pub fn dot(&self, other: &Vector<T>) -> T { self.x * other.x + self.y * other.y }
+
+    /// Applies the 2x2 linear transform `[a, b, c, d]` (row-major) to this vector.
+    pub fn transform(&self, matrix: &[T; 4]) -> Vector<T> {
+        Vector {
+            x: matrix[0] * self.x + matrix[1] * self.y,
+            y: matrix[2] * self.x + matrix[3] * self.y,
+        }
+    }
+}
+
+impl<T: Scalar + Neg<Output = T>> Vector<T> {
+    /// Rotates this vector a quarter turn counter-clockwise.
+    pub fn rotate90(&self) -> Vector<T> { Vector { x: -self.y, y: self.x } }
 }
 
-impl Point {
-    pub fn close_to(&self, other: &Point, epsilon: f32) -> bool { self.distance_2(other) < epsilon * epsilon }
+impl<T: FloatScalar> Vector<T> {
+    pub fn magnitude(&self) -> T { (self.x * self.x + self.y * self.y).sqrt() }
 
-    pub fn distance(&self, other: &Point) -> f32 { self.distance_2(other).sqrt() }
+    pub fn normalized(&self) -> Vector<T> {
+        let m = self.magnitude();
+        Vector { x: self.x / m, y: self.y / m }
+    }
+}
 
-    pub fn distance_2(&self, other: &Point) -> f32 {
+impl<T: FloatScalar + Neg<Output = T>> Vector<T> {
+    /// Rotates this vector by `angle` radians, counter-clockwise.
+    pub fn rotate(&self, angle: T) -> Vector<T> {
+        let (s, c) = (angle.sin(), angle.cos());
+        self.transform(&[c, -s, s, c])
+    }
+}
+
+impl<T: Scalar> Point<T> {
+    pub fn close_to(&self, other: &Point<T>, epsilon: T) -> bool { self.distance_2(other) < epsilon * epsilon }
+
+    pub fn distance_2(&self, other: &Point<T>) -> T {
         let dx = self.x - other.x;
         let dy = self.y - other.y;
         dx * dx + dy * dy
     }
+
+    /// Clamps each coordinate into `rect`'s `[left, right]`/`[top, bottom]`
+    /// range, returning the nearest point on or inside `rect`.
+    pub fn clamp(&self, rect: &Rect<T>) -> Point<T> {
+        Point {
+            x: self.x.rmax(rect.left()).rmin(rect.right()),
+            y: self.y.rmax(rect.top()).rmin(rect.bottom()),
+        }
+    }
+
+    /// Applies the 2x2 linear transform `[a, b, c, d]` (row-major) to this point.
+    pub fn transform(&self, matrix: &[T; 4]) -> Point<T> {
+        Point {
+            x: matrix[0] * self.x + matrix[1] * self.y,
+            y: matrix[2] * self.x + matrix[3] * self.y,
+        }
+    }
+}
+
+impl<T: FloatScalar> Point<T> {
+    pub fn distance(&self, other: &Point<T>) -> T { self.distance_2(other).sqrt() }
+}
+
+/// A SIMD-accelerated alternative to `Rect::intersects_any` for `f32`
+/// rects, following the same packed-comparison trick pathfinder uses for
+/// its glyph-outline AABB tests. Only the any-intersection test is
+/// vectorized; there is no SIMD counterpart to `Rect::filter_intersecting`.
+///
+/// Requires the nightly `portable_simd` feature, so it's gated behind the
+/// `simd` Cargo feature and compiles to nothing without it;
+/// `Rect::intersects_any` is always available as the scalar fallback.
+#[cfg(feature = "simd")]
+pub mod simd {
+    use super::Rect;
+    use std::simd::cmp::SimdPartialOrd;
+    use std::simd::f32x4;
+
+    /// Tests a query rect against up to four candidates at a time: rather
+    /// than packing one candidate's four sides into a vector (AoS), this
+    /// packs one side across four candidates (`left = [c0.left, c1.left,
+    /// c2.left, c3.left]`, and likewise for `top`/`right`/`bottom`), so each
+    /// of the four separating-axis comparisons `does_intersect` performs
+    /// scalarly becomes a single vectorized comparison across the whole
+    /// chunk, and the four results are OR'd together into one "separated"
+    /// mask.
+    pub fn intersects_any_f32(query: &Rect<f32>, candidates: &[Rect<f32>]) -> bool {
+        let q_left = f32x4::splat(query.left());
+        let q_top = f32x4::splat(query.top());
+        let q_right = f32x4::splat(query.right());
+        let q_bottom = f32x4::splat(query.bottom());
+
+        for chunk in candidates.chunks(4) {
+            let mut left = [f32::INFINITY; 4];
+            let mut top = [f32::INFINITY; 4];
+            let mut right = [f32::NEG_INFINITY; 4];
+            let mut bottom = [f32::NEG_INFINITY; 4];
+            for (i, c) in chunk.iter().enumerate() {
+                left[i] = c.left();
+                top[i] = c.top();
+                right[i] = c.right();
+                bottom[i] = c.bottom();
+            }
+
+            let left = f32x4::from_array(left);
+            let top = f32x4::from_array(top);
+            let right = f32x4::from_array(right);
+            let bottom = f32x4::from_array(bottom);
+
+            // does_intersect is !(left > q_right || right < q_left || top > q_bottom || bottom < q_top)
+            let separated = left.simd_gt(q_right) | right.simd_lt(q_left) | top.simd_gt(q_bottom) | bottom.simd_lt(q_top);
+            if !separated.all() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[test]
+fn from_points_f32() {
+    let r = Rect::from_points(&Point { x: 5.0, y: 5.0 }, &Point { x: -5.0, y: -5.0 });
+    assert_eq!(r.top_left, Point { x: -5.0, y: -5.0 });
+    assert_eq!(r.bottom_right, Point { x: 5.0, y: 5.0 });
+}
+
+#[test]
+fn from_points_i32() {
+    let r = Rect::from_points(&Point { x: 5i32, y: 5 }, &Point { x: -5i32, y: -5 });
+    assert_eq!(r.top_left, Point { x: -5i32, y: -5 });
+    assert_eq!(r.bottom_right, Point { x: 5i32, y: 5 });
+}
+
+#[test]
+fn split_quad_f64() {
+    let r = Rect::from_point_and_size(&Point { x: 0.0f64, y: 0.0 }, &Vector { x: 4.0, y: 4.0 });
+    let quads = r.split_quad();
+    assert_eq!(quads[0].width(), 2.0);
+    assert_eq!(quads[3].bottom_right, Point { x: 4.0, y: 4.0 });
+}
+
+#[test]
+fn ray_intersection_hits_front_face() {
+    let r = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    let t = r.ray_intersection(&Point { x: -5.0, y: 5.0 }, &Vector { x: 1.0, y: 0.0 });
+    assert_eq!(t, Some(5.0));
+}
+
+#[test]
+fn ray_intersection_misses() {
+    let r = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    let t = r.ray_intersection(&Point { x: -5.0, y: 20.0 }, &Vector { x: 1.0, y: 0.0 });
+    assert_eq!(t, None);
+}
+
+#[test]
+fn segment_intersection_stops_short() {
+    let r: Rect<f32> = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    let origin = Point { x: -5.0, y: 5.0 };
+    // `t` is parametric (in units of `dir`): the rect's left edge sits 5
+    // units from `origin`, so a 3-unit-long segment falls short of it...
+    assert_eq!(r.segment_intersection(&origin, &Vector { x: 3.0, y: 0.0 }), None);
+    // ...while a 6-unit-long segment reaches it at t = 5/6 of the way along.
+    let t = r.segment_intersection(&origin, &Vector { x: 6.0, y: 0.0 }).unwrap();
+    assert!((t - 5.0 / 6.0).abs() < 1e-6, "t = {}", t);
+}
+
+#[test]
+fn distance_to_point_inside_is_zero() {
+    let r = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    assert_eq!(r.distance_to_point(&Point { x: 5.0, y: 5.0 }), 0.0);
+}
+
+#[test]
+fn distance_to_point_outside_corner() {
+    let r = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    assert_eq!(r.distance_to_point(&Point { x: 13.0, y: 14.0 }), 5.0);
+}
+
+#[test]
+fn point_clamp() {
+    let r = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 10.0, y: 10.0 });
+    assert_eq!(Point { x: -3.0, y: 20.0 }.clamp(&r), Point { x: 0.0, y: 10.0 });
+}
+
+#[test]
+fn vector_rotate90() {
+    let v = Vector { x: 1.0, y: 0.0 };
+    assert_eq!(v.rotate90(), Vector { x: 0.0, y: 1.0 });
+}
+
+#[test]
+fn affine_rotation_transforms_bounds() {
+    use std::f32::consts::PI;
+
+    let r = Rect::from_points(&Point { x: -1.0, y: -1.0 }, &Point { x: 1.0, y: 1.0 });
+    let m = Affine2::rotation(PI / 2.0).with_translation(Vector { x: 10.0, y: 0.0 });
+    let bounds = r.transformed_bounds(&m);
+    assert!(bounds.close_to(&Rect::from_points(&Point { x: 9.0, y: -1.0 }, &Point { x: 11.0, y: 1.0 }), 0.001));
+}
+
+#[test]
+fn filter_intersecting_finds_matches() {
+    let query = Rect::from_points(&Point { x: 0.0, y: 0.0 }, &Point { x: 5.0, y: 5.0 });
+    let candidates = [
+        Rect::from_points(&Point { x: 1.0, y: 1.0 }, &Point { x: 2.0, y: 2.0 }),
+        Rect::from_points(&Point { x: 100.0, y: 100.0 }, &Point { x: 101.0, y: 101.0 }),
+        Rect::from_points(&Point { x: 4.0, y: 4.0 }, &Point { x: 6.0, y: 6.0 }),
+    ];
+    assert!(Rect::intersects_any(&query, &candidates));
+    assert_eq!(Rect::filter_intersecting(&query, &candidates), vec![0, 2]);
+}
+
+#[test]
+fn null_is_float_only() {
+    let r: Rect<f32> = Rect::null();
+    assert!(r.is_null());
+    assert!(!r.expanded_by(&Point { x: 1.0, y: 1.0 }).is_null());
+}
+
+#[test]
+fn try_null_some_for_floats_none_for_ints() {
+    assert!(Rect::<f32>::try_null().is_some());
+    assert!(Rect::<i32>::try_null().is_none());
 }
@@ -1,4 +1,8 @@
 #![deny(missing_docs)]
+// `geom::simd` is only compiled under the (nightly-only) `simd` Cargo
+// feature; this attribute is itself inert unless that feature is on, so it
+// doesn't affect stable builds.
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 //! A simple spacial partitioning data structure that allows fast queries for
 //! 2-dimensional objects.
@@ -33,6 +37,12 @@ pub trait Spatial {
 #[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Debug)]
 pub struct ItemId(u32);
 
+/// An index into a `QuadTree`'s node pool.  Handles are only ever valid for
+/// the tree that produced them, and are recycled through `free_list` once
+/// the node they point to is vacated.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+struct NodeHandle(u32);
+
 #[derive(Debug, Clone)]
 struct QuadTreeConfig {
     allow_duplicates: bool,
@@ -46,17 +56,19 @@ struct QuadTreeConfig {
 /// and querying objects in 3d space.
 #[derive(Debug, Clone)]
 pub struct QuadTree<T> {
-    root: QuadNode,
+    nodes: Vec<QuadNode>,
+    free_list: Vec<NodeHandle>,
+    root: NodeHandle,
     config: QuadTreeConfig,
     id: u32,
     elements: FnvHashMap<ItemId, (T, Rect)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum QuadNode {
     Branch {
         aabb: Rect,
-        children: [(Rect, Box<QuadNode>); 4],
+        children: [(Rect, NodeHandle); 4],
         in_all: Vec<(ItemId, Rect)>,
         element_count: usize,
         depth: usize,
@@ -68,43 +80,6 @@ enum QuadNode {
     },
 }
 
-impl Clone for QuadNode {
-    fn clone(&self) -> QuadNode {
-        match self {
-            &QuadNode::Branch {
-                ref aabb,
-                ref children,
-                ref in_all,
-                ref element_count,
-                ref depth,
-            } => {
-                let children = [
-                    children[0].clone(),
-                    children[1].clone(),
-                    children[2].clone(),
-                    children[3].clone(),
-                ];
-                QuadNode::Branch {
-                    aabb: aabb.clone(),
-                    children: children,
-                    in_all: in_all.clone(),
-                    element_count: element_count.clone(),
-                    depth: depth.clone(),
-                }
-            }
-            &QuadNode::Leaf {
-                ref aabb,
-                ref elements,
-                ref depth,
-            } => QuadNode::Leaf {
-                aabb: aabb.clone(),
-                elements: elements.clone(),
-                depth: depth.clone(),
-            },
-        }
-    }
-}
-
 impl<T> QuadTree<T> {
     /// Constructs a new QuadTree with customizable options.
     ///
@@ -114,12 +89,11 @@ impl<T> QuadTree<T> {
     /// * `max_children`: the maximum amount of children that a tree node will have before it gets split.
     /// * `max_depth`: the maximum depth that the tree can grow before it stops.
     pub fn new(size: Rect, allow_duplicates: bool, min_children: usize, max_children: usize, max_depth: usize) -> QuadTree<T> {
+        let root = QuadNode::new_leaf(size, 0, max_children);
         QuadTree {
-            root: QuadNode::Leaf {
-                aabb: size,
-                elements: Vec::with_capacity(max_children),
-                depth: 0,
-            },
+            nodes: vec![root],
+            free_list: Vec::new(),
+            root: NodeHandle(0),
             config: QuadTreeConfig {
                 allow_duplicates: allow_duplicates,
                 max_children: max_children,
@@ -144,18 +118,12 @@ impl<T> QuadTree<T> {
 
     /// Inserts an element with the provided bounding box.
     pub fn insert_with_box(&mut self, t: T, aabb: Rect) -> ItemId {
-        let &mut QuadTree {
-            ref mut root,
-            ref config,
-            ref mut id,
-            ref mut elements,
-        } = self;
-
-        let item_id = ItemId(*id);
-        *id += 1;
-
-        if root.insert(item_id, aabb, config) {
-            elements.insert(item_id, (t, aabb));
+        let item_id = ItemId(self.id);
+        self.id += 1;
+
+        let root = self.root;
+        if self.insert_at(root, item_id, aabb) {
+            self.elements.insert(item_id, (t, aabb));
         }
 
         item_id
@@ -185,7 +153,7 @@ impl<T> QuadTree<T> {
         T: ::std::fmt::Debug,
     {
         let mut ids = vec![];
-        self.root.query(bounding_box, &mut ids);
+        self.query_from(self.root, bounding_box, &mut ids);
         ids.sort_by_key(|&(id, _)| id);
         ids.dedup();
         ids.iter()
@@ -206,7 +174,8 @@ impl<T> QuadTree<T> {
     pub fn remove(&mut self, item_id: ItemId) -> Option<(T, Rect)> {
         match self.elements.remove(&item_id) {
             Some((item, aabb)) => {
-                self.root.remove(item_id, aabb, &self.config);
+                let root = self.root;
+                self.remove_at(root, item_id, aabb);
                 Some((item, aabb))
             }
             None => None,
@@ -221,7 +190,25 @@ impl<T> QuadTree<T> {
     /// * `&Rect`: The boudning box of that tree node
     /// * `usize`: The current depth
     /// * `bool`: True if the node is a leaf-node, False if the node is a branch node.
-    pub fn inspect<F: FnMut(&Rect, usize, bool)>(&self, mut f: F) { self.root.inspect(&mut f); }
+    pub fn inspect<F: FnMut(&Rect, usize, bool)>(&self, mut f: F) {
+        let mut stack = vec![self.root];
+        while let Some(handle) = stack.pop() {
+            match self.node(handle) {
+                &QuadNode::Branch {
+                    depth,
+                    ref aabb,
+                    ref children,
+                    ..
+                } => {
+                    f(aabb, depth, false);
+                    for &(_, child) in children {
+                        stack.push(child);
+                    }
+                }
+                &QuadNode::Leaf { depth, ref aabb, .. } => f(aabb, depth, true),
+            }
+        }
+    }
 
     /// Returns the number of elements in the tree
     pub fn len(&self) -> usize { self.elements.len() }
@@ -231,132 +218,156 @@ impl<T> QuadTree<T> {
 
     /// Returns the enclosing bounding-box for the entire tree.
     pub fn bounding_box(&self) -> Rect {
-        self.root.bounding_box()
+        self.node(self.root).bounding_box()
     }
-}
 
-impl QuadNode {
-    fn bounding_box(&self) -> Rect {
-        match self {
-            &QuadNode::Branch { ref aabb, .. } => aabb.clone(),
-            &QuadNode::Leaf { ref aabb, .. } => aabb.clone(),
+    fn node(&self, handle: NodeHandle) -> &QuadNode { &self.nodes[handle.0 as usize] }
+
+    fn node_mut(&mut self, handle: NodeHandle) -> &mut QuadNode { &mut self.nodes[handle.0 as usize] }
+
+    /// Stores `node` in a vacated pool slot if the free-list has one,
+    /// otherwise grows the pool.  Returns the handle it now lives at.
+    fn alloc_node(&mut self, node: QuadNode) -> NodeHandle {
+        if let Some(handle) = self.free_list.pop() {
+            *self.node_mut(handle) = node;
+            handle
+        } else {
+            let handle = NodeHandle(self.nodes.len() as u32);
+            self.nodes.push(node);
+            handle
         }
     }
 
-    fn new_leaf(aabb: Rect, depth: usize, config: &QuadTreeConfig) -> QuadNode {
-        QuadNode::Leaf {
-            aabb: aabb,
-            elements: Vec::with_capacity(config.max_children / 2),
-            depth: depth,
+    /// Releases every handle beneath `handle` back onto the free-list so
+    /// their pool slots can be reused by later splits. `handle` itself is
+    /// left alone, since callers collapsing a branch back into a leaf
+    /// overwrite that same slot in place rather than freeing it.
+    fn free_descendants(&mut self, handle: NodeHandle) {
+        if let &QuadNode::Branch { children, .. } = self.node(handle) {
+            for &(_, child) in children.iter() {
+                self.free_subtree(child);
+            }
         }
     }
 
-    fn inspect<F: FnMut(&Rect, usize, bool)>(&self, f: &mut F) {
-        match self {
-            &QuadNode::Branch {
-                depth,
-                ref aabb,
-                ref children,
-                ..
-            } => {
-                f(aabb, depth, false);
-                for child in children {
-                    child.1.inspect(f);
+    /// Releases `handle`, and every handle beneath it, back onto the
+    /// free-list so their pool slots can be reused by later splits.
+    fn free_subtree(&mut self, handle: NodeHandle) {
+        self.free_descendants(handle);
+        self.free_list.push(handle);
+    }
+
+    fn insert_at(&mut self, handle: NodeHandle, item_id: ItemId, item_aabb: Rect) -> bool {
+        enum Action {
+            InsertInAll,
+            Recurse([(Rect, NodeHandle); 4]),
+            InsertLeaf,
+            Split { aabb: Rect, depth: usize, extracted: Vec<(ItemId, Rect)> },
+        }
+
+        let allow_duplicates = self.config.allow_duplicates;
+        let epsilon = self.config.epsilon;
+        let max_children = self.config.max_children;
+        let max_depth = self.config.max_depth;
+
+        let action = match self.node(handle) {
+            &QuadNode::Branch { ref aabb, ref children, .. } => {
+                if item_aabb.contains(&aabb.midpoint()) {
+                    Action::InsertInAll
+                } else {
+                    Action::Recurse(*children)
                 }
             }
-            &QuadNode::Leaf { depth, ref aabb, .. } => {
-                f(aabb, depth, true);
+            &QuadNode::Leaf { ref aabb, ref elements, depth } => {
+                if elements.len() == max_children && depth != max_depth {
+                    let mut extracted = elements.clone();
+                    extracted.push((item_id, item_aabb));
+                    Action::Split { aabb: *aabb, depth: depth, extracted: extracted }
+                } else {
+                    Action::InsertLeaf
+                }
             }
-        }
-    }
+        };
 
-    fn insert(&mut self, item_id: ItemId, item_aabb: Rect, config: &QuadTreeConfig) -> bool {
-        let mut into = None;
-        let mut did_insert = false;
-        match self {
-            &mut QuadNode::Branch {
-                ref aabb,
-                ref mut in_all,
-                ref mut children,
-                ref mut element_count,
-                ..
-            } => {
-                if item_aabb.contains(&aabb.midpoint()) {
+        match action {
+            Action::InsertInAll => {
+                if let &mut QuadNode::Branch { ref mut in_all, ref mut element_count, .. } = self.node_mut(handle) {
                     // Only insert if there isn't another item with a very
                     // similar aabb.
-                    if config.allow_duplicates || !in_all.iter().any(|&(_, ref e_bb)| e_bb.close_to(&item_aabb, config.epsilon)) {
+                    if allow_duplicates || !in_all.iter().any(|&(_, ref e_bb)| e_bb.close_to(&item_aabb, epsilon)) {
                         in_all.push((item_id, item_aabb));
-                        did_insert = true;
                         *element_count += 1;
+                        true
+                    } else {
+                        false
                     }
                 } else {
-                    for &mut (ref aabb, ref mut child) in children {
-                        if aabb.does_intersect(&item_aabb) {
-                            if child.insert(item_id, item_aabb, config) {
+                    unreachable!()
+                }
+            }
+
+            Action::Recurse(children) => {
+                let mut did_insert = false;
+                for &(ref child_aabb, child_handle) in children.iter() {
+                    if child_aabb.does_intersect(&item_aabb) {
+                        if self.insert_at(child_handle, item_id, item_aabb) {
+                            did_insert = true;
+                            if let &mut QuadNode::Branch { ref mut element_count, .. } = self.node_mut(handle) {
                                 *element_count += 1;
-                                did_insert = true;
                             }
                         }
                     }
                 }
+                did_insert
             }
 
-            &mut QuadNode::Leaf {
-                ref aabb,
-                ref mut elements,
-                ref depth,
-            } => {
-                if elements.len() == config.max_children && *depth != config.max_depth {
-                    // STEAL ALL THE CHILDREN MUAHAHAHAHA
-                    let mut extracted_children = Vec::new();
-                    ::std::mem::swap(&mut extracted_children, elements);
-                    extracted_children.push((item_id, item_aabb));
-                    did_insert = true;
-
-                    let split = aabb.split_quad();
-                    into = Some((
-                        extracted_children,
-                        QuadNode::Branch {
-                            aabb: *aabb,
-                            in_all: Vec::new(),
-                            children: [
-                                (split[0], Box::new(QuadNode::new_leaf(split[0], depth + 1, config))),
-                                (split[1], Box::new(QuadNode::new_leaf(split[1], depth + 1, config))),
-                                (split[2], Box::new(QuadNode::new_leaf(split[2], depth + 1, config))),
-                                (split[3], Box::new(QuadNode::new_leaf(split[3], depth + 1, config))),
-                            ],
-                            element_count: 0,
-                            depth: *depth,
-                        },
-                    ));
-                } else {
-                    if config.allow_duplicates ||
+            Action::InsertLeaf => {
+                if let &mut QuadNode::Leaf { ref mut elements, .. } = self.node_mut(handle) {
+                    if allow_duplicates ||
                         !elements
                             .iter()
-                            .any(|&(_, ref e_bb)| e_bb.close_to(&item_aabb, config.epsilon))
+                            .any(|&(_, ref e_bb)| e_bb.close_to(&item_aabb, epsilon))
                     {
                         elements.push((item_id, item_aabb));
-                        did_insert = true;
+                        true
+                    } else {
+                        false
                     }
+                } else {
+                    unreachable!()
                 }
             }
-        }
 
-        // If we transitioned from a leaf node to a branch node, we
-        // need to update ourself and re-add all the children that
-        // we used to have
-        // in our this leaf into our new leaves.
-        if let Some((extracted_children, new_node)) = into {
-            *self = new_node;
-            for (child_id, child_aabb) in extracted_children {
-                self.insert(child_id, child_aabb, config);
+            // We outgrew this leaf.  Turn it into a branch over freshly
+            // allocated children and re-insert everything it used to hold
+            // (including the new item) through that branch, which puts
+            // items covering its midpoint in its own `in_all` list.
+            Action::Split { aabb, depth, extracted } => {
+                let split = aabb.split_quad();
+                let children = [
+                    self.alloc_node(QuadNode::new_leaf(split[0], depth + 1, max_children)),
+                    self.alloc_node(QuadNode::new_leaf(split[1], depth + 1, max_children)),
+                    self.alloc_node(QuadNode::new_leaf(split[2], depth + 1, max_children)),
+                    self.alloc_node(QuadNode::new_leaf(split[3], depth + 1, max_children)),
+                ];
+
+                *self.node_mut(handle) = QuadNode::Branch {
+                    aabb: aabb,
+                    in_all: Vec::new(),
+                    children: [(split[0], children[0]), (split[1], children[1]), (split[2], children[2]), (split[3], children[3])],
+                    element_count: 0,
+                    depth: depth,
+                };
+
+                for (child_id, child_aabb) in extracted {
+                    self.insert_at(handle, child_id, child_aabb);
+                }
+                true
             }
         }
-
-        did_insert
     }
 
-    fn remove(&mut self, item_id: ItemId, item_aabb: Rect, config: &QuadTreeConfig) -> bool {
+    fn remove_at(&mut self, handle: NodeHandle, item_id: ItemId, item_aabb: Rect) -> bool {
         fn remove_from(v: &mut Vec<(ItemId, Rect)>, item: ItemId) -> bool {
             if let Some(index) = v.iter().position(|a| a.0 == item) {
                 v.swap_remove(index);
@@ -366,74 +377,111 @@ impl QuadNode {
             }
         }
 
-        let mut compact = None;
-        let removed = match self {
-            &mut QuadNode::Branch {
-                ref depth,
-                ref aabb,
-                ref mut in_all,
-                ref mut children,
-                ref mut element_count,
-                ..
-            } => {
-                let mut did_remove = false;
+        let min_children = self.config.min_children;
 
-                if item_aabb.contains(&aabb.midpoint()) {
-                    did_remove = remove_from(in_all, item_id);
+        let in_all_branch = match self.node(handle) {
+            &QuadNode::Branch { ref aabb, .. } => Some(item_aabb.contains(&aabb.midpoint())),
+            &QuadNode::Leaf { .. } => None,
+        };
+
+        let did_remove = match in_all_branch {
+            Some(true) => {
+                if let &mut QuadNode::Branch { ref mut in_all, .. } = self.node_mut(handle) {
+                    remove_from(in_all, item_id)
                 } else {
-                    for &mut (ref child_aabb, ref mut child_tree) in children {
-                        if child_aabb.does_intersect(&item_aabb) {
-                            did_remove |= child_tree.remove(item_id, item_aabb, config);
-                        }
-                    }
+                    unreachable!()
                 }
-
-                if did_remove {
-                    *element_count -= 1;
-                    if *element_count < config.min_children {
-                        compact = Some((*element_count, *aabb, *depth));
+            }
+            Some(false) => {
+                let children = if let &QuadNode::Branch { ref children, .. } = self.node(handle) {
+                    *children
+                } else {
+                    unreachable!()
+                };
+                let mut did_remove = false;
+                for &(ref child_aabb, child_handle) in children.iter() {
+                    if child_aabb.does_intersect(&item_aabb) {
+                        did_remove |= self.remove_at(child_handle, item_id, item_aabb);
                     }
                 }
                 did_remove
             }
+            None => {
+                if let &mut QuadNode::Leaf { ref mut elements, .. } = self.node_mut(handle) {
+                    remove_from(elements, item_id)
+                } else {
+                    unreachable!()
+                }
+            }
+        };
 
-            &mut QuadNode::Leaf { ref mut elements, .. } => remove_from(elements, item_id),
+        let compact = if did_remove {
+            if let &mut QuadNode::Branch { ref mut element_count, .. } = self.node_mut(handle) {
+                *element_count -= 1;
+            }
+            if let &QuadNode::Branch { element_count, aabb, depth, .. } = self.node(handle) {
+                if element_count < min_children { Some((element_count, aabb, depth)) } else { None }
+            } else {
+                None
+            }
+        } else {
+            None
         };
 
         if let Some((size, aabb, depth)) = compact {
             let mut elements = Vec::with_capacity(size);
-            self.query(aabb, &mut elements);
+            self.query_from(handle, aabb, &mut elements);
             elements.sort_by(|&(id1, _), &(ref id2, _)| id1.cmp(id2));
             elements.dedup();
-            *self = QuadNode::Leaf {
+            self.free_descendants(handle);
+            *self.node_mut(handle) = QuadNode::Leaf {
                 aabb: aabb,
                 elements: elements,
                 depth: depth,
             };
         }
-        removed
+        did_remove
     }
 
-    fn query(&self, query_aabb: Rect, out: &mut Vec<(ItemId, Rect)>) {
-        fn match_all(elements: &Vec<(ItemId, Rect)>, query_aabb: Rect, out: &mut Vec<(ItemId, Rect)>) {
-            for &(ref child_id, ref child_aabb) in elements {
-                if query_aabb.does_intersect(child_aabb) {
-                    out.push((*child_id, *child_aabb))
+    fn query_from(&self, handle: NodeHandle, query_aabb: Rect, out: &mut Vec<(ItemId, Rect)>) {
+        fn match_all(elements: &[(ItemId, Rect)], query_aabb: Rect, out: &mut Vec<(ItemId, Rect)>) {
+            for &(child_id, child_aabb) in elements {
+                if query_aabb.does_intersect(&child_aabb) {
+                    out.push((child_id, child_aabb))
                 }
             }
         }
 
-        match self {
-            &QuadNode::Branch { ref in_all, ref children, .. } => {
-                match_all(in_all, query_aabb, out);
-
-                for &(ref child_aabb, ref child_tree) in children {
-                    if query_aabb.does_intersect(&child_aabb) {
-                        child_tree.query(query_aabb, out);
+        let mut stack = vec![handle];
+        while let Some(handle) = stack.pop() {
+            match self.node(handle) {
+                &QuadNode::Branch { ref in_all, ref children, .. } => {
+                    match_all(in_all, query_aabb, out);
+                    for &(ref child_aabb, child_handle) in children {
+                        if query_aabb.does_intersect(child_aabb) {
+                            stack.push(child_handle);
+                        }
                     }
                 }
+                &QuadNode::Leaf { ref elements, .. } => match_all(elements, query_aabb, out),
             }
-            &QuadNode::Leaf { ref elements, .. } => match_all(elements, query_aabb, out),
+        }
+    }
+}
+
+impl QuadNode {
+    fn bounding_box(&self) -> Rect {
+        match self {
+            &QuadNode::Branch { ref aabb, .. } => aabb.clone(),
+            &QuadNode::Leaf { ref aabb, .. } => aabb.clone(),
+        }
+    }
+
+    fn new_leaf(aabb: Rect, depth: usize, max_children: usize) -> QuadNode {
+        QuadNode::Leaf {
+            aabb: aabb,
+            elements: Vec::with_capacity(max_children / 2),
+            depth: depth,
         }
     }
 }